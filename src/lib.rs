@@ -3,114 +3,418 @@
 //!
 //! EAX is an AEAD (Authenticated Encryption with Associated Data) encryption
 //! scheme.
+//!
+//! This crate is `no_std`, so it runs on embedded targets without an
+//! allocator. Enable the `alloc` feature to pull in the allocating
+//! `aead::Aead`/`aead::AeadMut` convenience methods (`encrypt`/`decrypt`
+//! returning `Vec<u8>`), or the `heapless` feature to use a fixed-capacity
+//! `heapless::Vec` as the buffer of the in-place `aead::AeadInPlace`
+//! `encrypt_in_place`/`decrypt_in_place` methods.
+
+#![no_std]
+// `aead`/`cipher` 0.2-era crates pin `generic-array = "^0.14"`, which marks
+// itself `#[deprecated]` on any toolchain new enough to support its 1.x
+// successor. Silence that rather than the unrelated API we actually use.
+#![allow(deprecated)]
+
+mod stream;
+
+pub use crate::stream::{Decrypt, EaxStream, Encrypt};
+
+use core::marker::PhantomData;
 
-use block_cipher_trait::generic_array::functional::FunctionalSequence;
-use block_cipher_trait::generic_array::typenum::U16;
-use block_cipher_trait::generic_array::{ArrayLength, GenericArray};
-use block_cipher_trait::BlockCipher;
-use cmac::crypto_mac::MacResult;
-use cmac::{Cmac, Mac};
-use ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
+use aead::generic_array::functional::FunctionalSequence;
+use aead::generic_array::typenum::{IsGreaterOrEqual, IsLessOrEqual, True, U0, U16, U8};
+use aead::generic_array::{ArrayLength, GenericArray};
+use aead::{AeadInPlace, Error as AeadError, NewAead};
+use cipher::{BlockCipher, NewBlockCipher, NewStreamCipher, SyncStreamCipher};
+use cmac::{Cmac, Mac, NewMac};
 use subtle::ConstantTimeEq;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Maximum length of the plaintext/associated data/ciphertext EAX will
+/// process, in bytes (2^36). Longer inputs are rejected with an error.
+///
+/// This crate's API is detached-tag only (the tag is always a separate
+/// argument, never appended to the ciphertext buffer), so the same limit
+/// applies to plaintext and ciphertext alike.
+pub const P_MAX: u64 = 1 << 36;
+/// See [`P_MAX`].
+pub const A_MAX: u64 = 1 << 36;
+
+/// CMAC/OMAC1, prepending the 16 byte, zero-padded `iv` block the EAX
+/// construction uses to domain-separate the nonce/header/ciphertext OMACs.
+///
+/// [`Eax`] derives `n`, `h` and `c` with it; [`EaxStream`] derives `n` with
+/// it and seeds its running header/ciphertext OMACs with the same prefix
+/// block directly.
+pub(crate) fn cmac_with_iv<C>(
+	key: &GenericArray<u8, C::KeySize>,
+	iv: u8,
+	data: &[u8],
+) -> GenericArray<u8, <Cmac<C> as Mac>::OutputSize>
+where C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone
+{
+	let mut mac = Cmac::<C>::new(key);
+	mac.update(&[0; 15]);
+	mac.update(&[iv]);
+	mac.update(data);
+
+	mac.finalize().into_bytes()
+}
 
-pub struct Eax<C: BlockCipher<BlockSize = U16> + Clone>
-where C::ParBlocks: ArrayLength<GenericArray<u8, U16>>
+/// EAX, constructed with a key and ready to use through the RustCrypto
+/// [`aead`] traits ([`NewAead`], [`AeadInPlace`]) or through its own
+/// [`Eax::encrypt`]/[`Eax::decrypt`] associated functions.
+///
+/// `TagSize` is the number of bytes of the 16-byte EAX tag that are kept; it
+/// defaults to the full tag (`U16`). Tags shorter than 8 bytes are rejected
+/// at compile time, so a truncated tag is an explicit, audited choice
+/// rather than an accident of slice length.
+pub struct Eax<C, TagSize = U16>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
 {
-	phantom: std::marker::PhantomData<C>,
+	key: GenericArray<u8, C::KeySize>,
+	tag_size: PhantomData<TagSize>,
 }
 
-impl<C: BlockCipher<BlockSize = U16> + Clone> Eax<C>
-where C::ParBlocks: ArrayLength<GenericArray<u8, U16>>
+impl<C, TagSize> Eax<C, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
 {
 	/// Encrypt and authenticate data.
 	///
 	/// # Arguments
 	/// - `key`: The key to use for encryption.
-	/// - `nonce`: The nonce to use for encryption.
+	/// - `nonce`: The nonce to use for encryption. Unlike the key, this may
+	///   be of any length: the OMAC construction normalizes it to one
+	///   block before use.
 	/// - `header`: Associated data, which will also be authenticated.
 	/// - `data`: The data which will be encrypted in-place.
 	///
 	/// # Return value
-	/// tag/mac
+	/// tag/mac, truncated to `TagSize` bytes, or an error if `header` or
+	/// `data` exceed the EAX length limits ([`A_MAX`]/[`P_MAX`]).
 	pub fn encrypt(
 		key: &GenericArray<u8, C::KeySize>,
-		nonce: &GenericArray<u8, C::KeySize>,
+		nonce: &[u8],
 		header: &[u8],
 		data: &mut [u8],
-	) -> GenericArray<u8, <Cmac<C> as Mac>::OutputSize>
+	) -> Result<GenericArray<u8, TagSize>, AeadError>
 	{
 		// https://crypto.stackexchange.com/questions/26948/eax-cipher-mode-with-nonce-equal-header
 		// has an explanation of eax.
 
+		if header.len() as u64 > A_MAX || data.len() as u64 > P_MAX {
+			return Err(AeadError);
+		}
+
 		// l = block cipher size = 128 (for AES-128) = 16 byte
 		// 1. n ← OMAC(0 || Nonce)
 		// (the 0 means the number zero in l bits)
-		let n = Self::cmac_with_iv(key, 0, nonce).code();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut n = cmac_with_iv::<C>(key, 0, nonce);
 
 		// 2. h ← OMAC(1 || associated data)
-		let h = Self::cmac_with_iv(key, 1, header).code();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut h = cmac_with_iv::<C>(key, 1, header);
 
 		// 3. enc ← CTR(M) using n as iv
 		let mut cipher = ctr::Ctr128::<C>::new(key, &n);
 		cipher.apply_keystream(data);
 
 		// 4. c ← OMAC(2 || enc)
-		let c = Self::cmac_with_iv(key, 2, data).code();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut c = cmac_with_iv::<C>(key, 2, data);
 
 		// 5. tag ← n ^ h ^ c
 		// (^ means xor)
-		n.zip(h, |a, b| a ^ b).zip(c, |a, b| a ^ b)
+		let tag = n.zip(h, |a, b| a ^ b).zip(c, |a, b| a ^ b);
+
+		#[cfg(feature = "zeroize")]
+		{
+			n.zeroize();
+			h.zeroize();
+			c.zeroize();
+		}
+
+		Ok(GenericArray::clone_from_slice(&tag[..TagSize::to_usize()]))
 	}
 
 	/// Check authentication and decrypt data.
+	///
+	/// `mac` must be exactly `TagSize` bytes; there is no partial-tag
+	/// fallback. Returns an error if authentication fails or if `header`
+	/// or `data` exceed the EAX length limits ([`A_MAX`]/[`P_MAX`]).
 	pub fn decrypt(
 		key: &GenericArray<u8, C::KeySize>,
-		nonce: &GenericArray<u8, C::KeySize>,
+		nonce: &[u8],
 		header: &[u8],
 		data: &mut [u8],
-		mac: &[u8],
-	) -> Result<(), cmac::crypto_mac::MacError>
+		mac: &GenericArray<u8, TagSize>,
+	) -> Result<(), AeadError>
 	{
+		if header.len() as u64 > A_MAX || data.len() as u64 > P_MAX {
+			return Err(AeadError);
+		}
+
 		// 2. n ← OMAC(0 || Nonce)
-		let n = Self::cmac_with_iv(key, 0, nonce).code();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut n = cmac_with_iv::<C>(key, 0, nonce);
 
 		// 2. h ← OMAC(1 || associated data)
-		let h = Self::cmac_with_iv(key, 1, header).code();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut h = cmac_with_iv::<C>(key, 1, header);
 
 		// 4. c ← OMAC(2 || enc)
-		let c = Self::cmac_with_iv(key, 2, data).code();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut c = cmac_with_iv::<C>(key, 2, data);
 
-		let mac2 = n.zip(h, |a, b| a ^ b).zip(c, |a, b| a ^ b);
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut mac2 = n.zip(h, |a, b| a ^ b).zip(c, |a, b| a ^ b);
 
-		// Take only the needed length
-		let mac2 = &mac2[..mac.len()];
+		// Take only the agreed-on length
+		let matches = mac.as_slice().ct_eq(&mac2[..TagSize::to_usize()]).unwrap_u8() == 1;
+
+		#[cfg(feature = "zeroize")]
+		{
+			h.zeroize();
+			c.zeroize();
+			mac2.zeroize();
+		}
 
 		// Check mac using secure comparison
-		if mac.ct_eq(mac2).unwrap_u8() != 1 {
-			return Err(cmac::crypto_mac::MacError);
+		if !matches {
+			return Err(AeadError);
 		}
 
 		// Decrypt
 		let mut cipher = ctr::Ctr128::<C>::new(key, &n);
 		cipher.apply_keystream(data);
+
+		#[cfg(feature = "zeroize")]
+		n.zeroize();
+
 		Ok(())
 	}
+}
 
-	/// CMAC/OMAC1
-	///
-	/// To avoid constructing new buffers on the heap, an iv encoded into 16
-	/// bytes is prepended inside this function.
-	fn cmac_with_iv(
-		key: &GenericArray<u8, C::KeySize>,
-		iv: u8,
-		data: &[u8],
-	) -> MacResult<<Cmac<C> as Mac>::OutputSize>
+impl<C, TagSize> NewAead for Eax<C, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	type KeySize = C::KeySize;
+
+	fn new(key: &GenericArray<u8, Self::KeySize>) -> Self {
+		Eax { key: key.clone(), tag_size: PhantomData }
+	}
+}
+
+impl<C, TagSize> AeadInPlace for Eax<C, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	type NonceSize = U16;
+	type TagSize = TagSize;
+	type CiphertextOverhead = U0;
+
+	fn encrypt_in_place_detached(
+		&self,
+		nonce: &GenericArray<u8, Self::NonceSize>,
+		associated_data: &[u8],
+		buffer: &mut [u8],
+	) -> Result<GenericArray<u8, Self::TagSize>, AeadError>
 	{
-		let mut mac = Cmac::<C>::new(key);
-		mac.input(&[0; 15]);
-		mac.input(&[iv]);
-		mac.input(data);
+		Self::encrypt(&self.key, nonce, associated_data, buffer)
+	}
+
+	fn decrypt_in_place_detached(
+		&self,
+		nonce: &GenericArray<u8, Self::NonceSize>,
+		associated_data: &[u8],
+		buffer: &mut [u8],
+		tag: &GenericArray<u8, Self::TagSize>,
+	) -> Result<(), AeadError>
+	{
+		Self::decrypt(&self.key, nonce, associated_data, buffer, tag)
+	}
+}
+
+#[cfg(feature = "getrandom")]
+fn random_array<N: ArrayLength<u8>>() -> Result<GenericArray<u8, N>, getrandom::Error> {
+	let mut array = GenericArray::default();
+	getrandom::getrandom(&mut array)?;
+	Ok(array)
+}
+
+#[cfg(feature = "getrandom")]
+impl<C, TagSize> Eax<C, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	/// Generate a random key, suitable for use with this cipher, from the
+	/// OS CSPRNG.
+	///
+	/// This avoids a common misuse: a hand-rolled or reused key/nonce.
+	pub fn generate_key() -> Result<GenericArray<u8, C::KeySize>, getrandom::Error> { random_array() }
+}
+
+/// Generate a random 128-bit nonce, suitable for use with [`Eax`] or
+/// [`EaxStream`], from the OS CSPRNG.
+#[cfg(feature = "getrandom")]
+pub fn generate_nonce() -> Result<GenericArray<u8, U16>, getrandom::Error> { random_array() }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use aes::Aes128;
+
+	// Self-derived, NOT an official published EAX test vector: computed and
+	// cross-checked against an independent Python implementation of the
+	// construction described in the EAX paper, not taken from a standards
+	// document.
+	#[test]
+	fn self_derived_vector() {
+		let key = GenericArray::clone_from_slice(&[
+			0x23, 0x39, 0x52, 0xde, 0xe4, 0xd5, 0xed, 0x5f, 0x9b, 0x9c, 0x6d, 0x6f, 0xf8, 0x0f,
+			0xf4, 0x78,
+		]);
+		let nonce = [
+			0x62, 0xec, 0x67, 0xf9, 0xc3, 0xa4, 0xa4, 0x07, 0xfc, 0xb2, 0xa8, 0xc4, 0x90, 0x31,
+			0xa8, 0xb3,
+		];
+		let header = [0x6b, 0xfb, 0x91, 0x4f, 0xd0, 0x7e, 0xae, 0x6b];
+		let plaintext = b"hello, eax world!!";
+		let expected_ciphertext = [
+			0x2f, 0x9f, 0x76, 0xcb, 0x76, 0x55, 0x90, 0x04, 0x58, 0xf5, 0x52, 0x26, 0xed, 0xbd,
+			0x93, 0x8c, 0xa5, 0x03,
+		];
+		let expected_tag = [
+			0x3d, 0x3b, 0x7a, 0x1c, 0x06, 0x61, 0x6a, 0x4a, 0x5e, 0x0e, 0xbc, 0x09, 0x95, 0x3c,
+			0x95, 0x08,
+		];
+
+		let mut data = *plaintext;
+		let tag = Eax::<Aes128>::encrypt(&key, &nonce, &header, &mut data).unwrap();
+		assert_eq!(data, expected_ciphertext);
+		assert_eq!(tag.as_slice(), expected_tag);
+
+		Eax::<Aes128>::decrypt(&key, &nonce, &header, &mut data, &tag).unwrap();
+		assert_eq!(data, *plaintext);
+	}
+
+	#[test]
+	fn truncated_tag_size_round_trips() {
+		use aead::generic_array::typenum::U10;
+
+		let key = GenericArray::clone_from_slice(&[0x11; 16]);
+		let nonce = [0x22; 16];
+		let header = b"associated data";
+		let plaintext = b"hello, eax world!!";
+
+		// The truncated tag must be a correct prefix of the full tag, not
+		// something separately derived.
+		let mut full_tag_data = *plaintext;
+		let full_tag = Eax::<Aes128>::encrypt(&key, &nonce, header, &mut full_tag_data).unwrap();
+
+		let mut data = *plaintext;
+		let tag = Eax::<Aes128, U10>::encrypt(&key, &nonce, header, &mut data).unwrap();
+		assert_eq!(tag.len(), 10);
+		assert_eq!(tag.as_slice(), &full_tag[..10]);
+		assert_eq!(data, full_tag_data);
+		let ciphertext = data;
+
+		Eax::<Aes128, U10>::decrypt(&key, &nonce, header, &mut data, &tag).unwrap();
+		assert_eq!(data, *plaintext);
+
+		// EaxStream with the same TagSize agrees with the one-shot API.
+		let mut stream_data = *plaintext;
+		let mut stream = EaxStream::<Aes128, Encrypt, U10>::new(&key, &nonce);
+		stream.update_header(header).unwrap();
+		stream.update(&mut stream_data).unwrap();
+		let stream_tag = stream.finish();
+		assert_eq!(stream_tag, tag);
+
+		let mut decrypt_stream = EaxStream::<Aes128, Decrypt, U10>::new(&key, &nonce);
+		decrypt_stream.update_header(header).unwrap();
+		decrypt_stream.update(&mut stream_data).unwrap();
+		decrypt_stream.finish(&stream_tag).unwrap();
+		assert_eq!(stream_data, *plaintext);
+
+		// A tampered tag must be rejected, not silently accepted as a valid
+		// (shorter) prefix.
+		let mut bad_tag = tag;
+		bad_tag[0] ^= 1;
+		let mut tampered_data = ciphertext;
+		assert!(Eax::<Aes128, U10>::decrypt(&key, &nonce, header, &mut tampered_data, &bad_tag).is_err());
+	}
+
+	#[test]
+	fn stream_matches_one_shot_encrypt() {
+		let key = GenericArray::clone_from_slice(&[0x42; 16]);
+		let nonce = [0x24; 16];
+		let header = b"associated data";
+		let plaintext = b"hello, eax world!!";
+
+		let mut one_shot_data = *plaintext;
+		let one_shot_tag = Eax::<Aes128>::encrypt(&key, &nonce, header, &mut one_shot_data).unwrap();
+
+		let mut stream_data = *plaintext;
+		let mut stream = EaxStream::<Aes128, Encrypt>::new(&key, &nonce);
+		stream.update_header(header).unwrap();
+		stream.update(&mut stream_data).unwrap();
+		let stream_tag = stream.finish();
+
+		assert_eq!(stream_data, one_shot_data);
+		assert_eq!(stream_tag.as_slice(), one_shot_tag.as_slice());
+
+		let mut decrypt_stream = EaxStream::<Aes128, Decrypt>::new(&key, &nonce);
+		decrypt_stream.update_header(header).unwrap();
+		decrypt_stream.update(&mut stream_data).unwrap();
+		decrypt_stream.finish(&stream_tag).unwrap();
+		assert_eq!(stream_data, *plaintext);
+	}
+
+	#[cfg(feature = "alloc")]
+	#[test]
+	fn alloc_feature_provides_aead_convenience_methods() {
+		use aead::Aead;
+
+		let key = GenericArray::clone_from_slice(&[0; 16]);
+		let nonce = GenericArray::clone_from_slice(&[0; 16]);
+		let eax = Eax::<Aes128>::new(&key);
+
+		let ciphertext = eax.encrypt(&nonce, b"hello, eax world!!".as_ref()).unwrap();
+		let plaintext = eax.decrypt(&nonce, ciphertext.as_slice()).unwrap();
+		assert_eq!(plaintext, b"hello, eax world!!");
+	}
+
+	#[cfg(feature = "heapless")]
+	#[test]
+	fn heapless_feature_provides_fixed_capacity_buffer_support() {
+		use aead::{generic_array::typenum::U64, AeadInPlace};
+		use heapless::Vec as HeaplessVec;
+
+		let key = GenericArray::clone_from_slice(&[0; 16]);
+		let nonce = GenericArray::clone_from_slice(&[0; 16]);
+		let eax = Eax::<Aes128>::new(&key);
+		let plaintext = b"hello, eax world!!";
+
+		// `heapless::Vec` only needs to implement `aead::Buffer` for this to
+		// work, which the `heapless` feature on `aead` provides.
+		let mut buffer: HeaplessVec<u8, U64> = HeaplessVec::from_slice(plaintext).unwrap();
+		eax.encrypt_in_place(&nonce, b"", &mut buffer).unwrap();
 
-		mac.result()
+		let mut expected = *plaintext;
+		let tag = eax.encrypt_in_place_detached(&nonce, b"", &mut expected).unwrap();
+		assert_eq!(&buffer[..plaintext.len()], &expected[..]);
+		assert_eq!(&buffer[plaintext.len()..], tag.as_slice());
 	}
 }
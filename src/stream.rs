@@ -0,0 +1,226 @@
+//! Incremental, chunk-at-a-time EAX encryption/decryption.
+//!
+//! [`Eax::encrypt`](crate::Eax::encrypt)/[`Eax::decrypt`](crate::Eax::decrypt)
+//! need the whole message in one contiguous, mutable slice, which does not
+//! work for large files or network streams. [`EaxStream`] processes the
+//! header and the data incrementally instead, so callers can feed it
+//! whatever chunks they happen to have on hand.
+
+use core::marker::PhantomData;
+
+use aead::generic_array::functional::FunctionalSequence;
+use aead::generic_array::typenum::{IsGreaterOrEqual, IsLessOrEqual, True, U16, U8};
+use aead::generic_array::{ArrayLength, GenericArray};
+use aead::Error as AeadError;
+use cipher::{BlockCipher, NewBlockCipher, NewStreamCipher, SyncStreamCipher};
+use cmac::{Cmac, Mac, NewMac};
+use subtle::ConstantTimeEq;
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+use crate::{cmac_with_iv, A_MAX, P_MAX};
+
+/// Marker type selecting encryption mode for [`EaxStream`].
+pub struct Encrypt;
+
+/// Marker type selecting decryption mode for [`EaxStream`].
+pub struct Decrypt;
+
+/// Incremental EAX encryption/decryption.
+///
+/// Construct with [`EaxStream::new`], feed associated data through
+/// [`EaxStream::update_header`] and message chunks through
+/// [`EaxStream::update`] (the two may be interleaved freely, they
+/// accumulate into independent OMAC states), then call
+/// [`EaxStream::finish`] to obtain the tag (`Op = `[`Encrypt`]) or to
+/// verify it (`Op = `[`Decrypt`]).
+///
+/// `TagSize` has the same meaning as on [`Eax`](crate::Eax): the number of
+/// bytes of the 16-byte tag that are kept, defaulting to the full tag
+/// (`U16`).
+pub struct EaxStream<C, Op, TagSize = U16>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	cipher: ctr::Ctr128<C>,
+	n: GenericArray<u8, U16>,
+	header_mac: Cmac<C>,
+	header_len: u64,
+	data_mac: Cmac<C>,
+	data_len: u64,
+	tag_size: PhantomData<TagSize>,
+	phantom: PhantomData<Op>,
+}
+
+#[cfg(feature = "zeroize")]
+impl<C, Op, TagSize> Drop for EaxStream<C, Op, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	/// Wipe the CTR IV on drop, not just on the `finish()` happy path, so a
+	/// stream abandoned after an `update`/`update_header` error (or simply
+	/// never finished) doesn't leave it behind.
+	///
+	/// `header_mac`/`data_mac`'s internal state (derived subkeys, partial
+	/// block buffer) is not wiped here: `Cmac` keeps those fields private
+	/// and doesn't implement `Zeroize` itself, so there's nothing this impl
+	/// can reach into.
+	fn drop(&mut self) { self.n.zeroize(); }
+}
+
+impl<C, Op, TagSize> EaxStream<C, Op, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	/// Start a new incremental EAX operation.
+	///
+	/// # Arguments
+	/// - `key`: The key to use for encryption/decryption.
+	/// - `nonce`: The nonce to use for encryption/decryption. May be of any
+	///   length, independent of the key size.
+	pub fn new(key: &GenericArray<u8, C::KeySize>, nonce: &[u8]) -> Self {
+		// 1. n ← OMAC(0 || Nonce)
+		let n = cmac_with_iv::<C>(key, 0, nonce);
+		let cipher = ctr::Ctr128::<C>::new(key, &n);
+
+		// 2. h ← OMAC(1 || associated data), 4. c ← OMAC(2 || enc)
+		// Both are seeded here and fed incrementally by `update`/
+		// `update_header` as chunks come in.
+		let mut header_mac = Cmac::<C>::new(key);
+		header_mac.update(&[0; 15]);
+		header_mac.update(&[1]);
+
+		let mut data_mac = Cmac::<C>::new(key);
+		data_mac.update(&[0; 15]);
+		data_mac.update(&[2]);
+
+		EaxStream {
+			cipher,
+			n,
+			header_mac,
+			header_len: 0,
+			data_mac,
+			data_len: 0,
+			tag_size: PhantomData,
+			phantom: PhantomData,
+		}
+	}
+
+	/// Feed a chunk of associated data into the running header OMAC.
+	///
+	/// May be called any number of times, interleaved with
+	/// [`EaxStream::update`]. Returns an error, without updating the OMAC,
+	/// if the cumulative header length would exceed [`A_MAX`].
+	pub fn update_header(&mut self, header: &[u8]) -> Result<(), AeadError> {
+		let len = self.header_len + header.len() as u64;
+		if len > A_MAX {
+			return Err(AeadError);
+		}
+		self.header_mac.update(header);
+		self.header_len = len;
+		Ok(())
+	}
+}
+
+impl<C, TagSize> EaxStream<C, Encrypt, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	/// Encrypt a chunk of data in place and feed the resulting ciphertext
+	/// into the running ciphertext OMAC.
+	///
+	/// Returns an error, without touching `buffer`, if the cumulative data
+	/// length would exceed [`P_MAX`].
+	pub fn update(&mut self, buffer: &mut [u8]) -> Result<(), AeadError> {
+		let len = self.data_len + buffer.len() as u64;
+		if len > P_MAX {
+			return Err(AeadError);
+		}
+		self.cipher.apply_keystream(buffer);
+		self.data_mac.update(buffer);
+		self.data_len = len;
+		Ok(())
+	}
+
+	/// Finish encryption and return the tag, truncated to `TagSize` bytes.
+	pub fn finish(self) -> GenericArray<u8, TagSize> {
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut n = self.n;
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut h = self.header_mac.clone().finalize().into_bytes();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut c = self.data_mac.clone().finalize().into_bytes();
+
+		// 5. tag ← n ^ h ^ c
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut tag = n.zip(h, |a, b| a ^ b).zip(c, |a, b| a ^ b);
+		let truncated = GenericArray::clone_from_slice(&tag[..TagSize::to_usize()]);
+
+		#[cfg(feature = "zeroize")]
+		{
+			n.zeroize();
+			h.zeroize();
+			c.zeroize();
+			tag.zeroize();
+		}
+
+		truncated
+	}
+}
+
+impl<C, TagSize> EaxStream<C, Decrypt, TagSize>
+where
+	C: BlockCipher<BlockSize = U16> + NewBlockCipher + Clone,
+	TagSize: ArrayLength<u8> + IsLessOrEqual<U16, Output = True> + IsGreaterOrEqual<U8, Output = True>,
+{
+	/// Feed a chunk of ciphertext into the running ciphertext OMAC, then
+	/// decrypt it in place.
+	///
+	/// The ciphertext (not the plaintext) is what EAX authenticates, so
+	/// the bytes must reach the OMAC *before* the keystream is applied.
+	///
+	/// Returns an error, without touching `buffer`, if the cumulative data
+	/// length would exceed [`P_MAX`].
+	pub fn update(&mut self, buffer: &mut [u8]) -> Result<(), AeadError> {
+		let len = self.data_len + buffer.len() as u64;
+		if len > P_MAX {
+			return Err(AeadError);
+		}
+		self.data_mac.update(buffer);
+		self.cipher.apply_keystream(buffer);
+		self.data_len = len;
+		Ok(())
+	}
+
+	/// Finish decryption, checking `mac` (exactly `TagSize` bytes) in
+	/// constant time.
+	pub fn finish(self, mac: &GenericArray<u8, TagSize>) -> Result<(), AeadError> {
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut n = self.n;
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut h = self.header_mac.clone().finalize().into_bytes();
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut c = self.data_mac.clone().finalize().into_bytes();
+
+		#[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+		let mut tag = n.zip(h, |a, b| a ^ b).zip(c, |a, b| a ^ b);
+		let matches = mac.as_slice().ct_eq(&tag[..TagSize::to_usize()]).unwrap_u8() == 1;
+
+		#[cfg(feature = "zeroize")]
+		{
+			n.zeroize();
+			h.zeroize();
+			c.zeroize();
+			tag.zeroize();
+		}
+
+		if !matches {
+			return Err(AeadError);
+		}
+		Ok(())
+	}
+}